@@ -2,15 +2,80 @@
 extern crate irc;
 #[cfg(feature = "ircbot")]
 extern crate glob;
-#[cfg(feature = "ircbot")]
+#[cfg(any(feature = "ircbot", feature = "stdio"))]
 extern crate rink;
 
-#[cfg(feature = "ircbot")]
-fn main() {
-    use irc::client::prelude::*;
+// Shared dispatch logic for any chat transport the bot can run
+// against: strip the bot's nick prefix, evaluate the query, and
+// truncate the reply to the frontend's configured line limit. Each
+// transport only needs to supply where messages come from and how a
+// line gets sent back.
+#[cfg(any(feature = "ircbot", feature = "stdio"))]
+mod frontend {
     use rink::*;
-    use glob::glob;
-    use std::thread;
+    #[cfg(not(feature = "sandbox"))]
+    use std::str::FromStr;
+
+    /// Where a reply should be sent: a channel, a nick, or whatever
+    /// else a transport considers an addressable destination.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct ReplyTarget(pub String);
+
+    pub trait ChatFrontend {
+        /// The bot's own nick, used to recognize `nick: query` lines.
+        fn nick(&self) -> &str;
+
+        /// A stream of raw `(where to reply, asking nick, message
+        /// text)` triples. Lines that don't start with this
+        /// frontend's nick prefix are ignored by `run`.
+        fn incoming<'a>(&'a self) -> Box<Iterator<Item = (ReplyTarget, String, String)> + 'a>;
+
+        /// Sends one line of a reply back to `target`.
+        fn send(&self, target: &ReplyTarget, line: &str);
+
+        /// How many lines of a reply to send before truncating with
+        /// a "…(N more lines)" marker. Transports with their own
+        /// flood limits (e.g. IRC) should override this.
+        fn max_reply_lines(&self) -> usize {
+            5
+        }
+
+        /// Called once per incoming line that's actually addressed to
+        /// the bot (i.e. after the nick-prefix check), before it's
+        /// evaluated. Returning `false` silently drops the query.
+        /// Transports with per-nick flood limits (e.g. IRC) override
+        /// this; by default every addressed query is allowed.
+        fn allow_query(&self, sender: &str) -> bool {
+            let _ = sender;
+            true
+        }
+
+        fn run(&self) {
+            let mut prefix = self.nick().to_owned();
+            prefix.push(':');
+            for (target, sender, message) in self.incoming() {
+                if !message.starts_with(&*prefix) {
+                    continue;
+                }
+                if !self.allow_query(&sender) {
+                    continue;
+                }
+                let line = message[prefix.len()..].trim();
+                let reply = handle(line);
+                let mut lines: Vec<&str> = reply.lines()
+                    .filter(|l| l.trim().len() > 0)
+                    .collect();
+                let overflow = lines.len().saturating_sub(self.max_reply_lines());
+                lines.truncate(self.max_reply_lines());
+                for line in &lines {
+                    self.send(&target, line);
+                }
+                if overflow > 0 {
+                    self.send(&target, &format!("…({} more lines)", overflow));
+                }
+            }
+        }
+    }
 
     #[cfg(feature = "sandbox")]
     fn eval(line: &str) -> String {
@@ -21,50 +86,240 @@ fn main() {
     fn eval(line: &str) -> String {
         let mut ctx = load().unwrap();
         ctx.short_output = true;
-        match one_line(&mut ctx, line) {
-            Ok(v) => v,
-            Err(e) => e
-        }
-    }
-
-    fn run(config: &str) {
-        let server = IrcServer::new(config).unwrap();
-        server.identify().unwrap();
-        let nick = server.config().nickname.clone().unwrap();
-        let mut prefix = nick.clone();
-        prefix.push(':');
-        for message in server.iter() {
-            if let Ok(Message { command: Command::PRIVMSG(ref chan, ref message_str), ..}) = message {
-                if message_str.starts_with(&*prefix) {
-                    let reply_to = if &*chan == &*nick {
-                        message.as_ref().unwrap().source_nickname().unwrap()
-                    } else {
-                        &*chan
-                    };
-                    let line = message_str[prefix.len()..].trim();
-                    let mut i = 0;
-                    let reply = eval(line);
-                    for line in reply.lines() {
-                        if line.trim().len() > 0 {
-                            server.send(Command::NOTICE(reply_to.to_owned(), line.to_owned())).unwrap();
-                            i += 1;
-                        }
-                        // cut off early
-                        if i > 4 {
-                            break;
-                        }
+        // A trailing `=> <conversion>` clause names a `Conversion` for
+        // the result: rink's text reply is read back with
+        // `Conversion::parse` and written out with `Conversion::format`
+        // in its place. The reply is parsed as text, not as a rink
+        // value, so `timestamp_fmt("<format>")` accepts only a reply
+        // already written in that format; a numeric result such as
+        // `now - epoch` gets a parse error, not a formatted date.
+        // `=>` rather than `->` so this doesn't collide with rink's
+        // own `<expr> -> <unit>` conversion syntax (e.g. `1 kWh -> J`).
+        match split_conversion_clause(line) {
+            (query, Some(spec)) => match spec.parse::<Conversion>() {
+                Ok(conversion) => match one_line(&mut ctx, query) {
+                    Ok(reply) => match conversion.parse(reply.trim())
+                        .and_then(|value| conversion.format(&value, &ctx)) {
+                        Ok(v) => v,
+                        Err(e) => e
+                    },
+                    Err(e) => e
+                },
+                Err(e) => e.to_string()
+            },
+            (query, None) => match one_line(&mut ctx, query) {
+                Ok(v) => v,
+                Err(e) => e
+            }
+        }
+    }
+
+    #[cfg(not(feature = "sandbox"))]
+    fn split_conversion_clause(line: &str) -> (&str, Option<&str>) {
+        match line.rfind("=>") {
+            Some(pos) => (line[..pos].trim(), Some(line[pos + 2..].trim())),
+            None => (line, None)
+        }
+    }
+
+    /// Wraps `one_line`/`one_line_sandbox` so every frontend evaluates
+    /// queries the same way.
+    pub fn handle(line: &str) -> String {
+        eval(line)
+    }
+
+    #[cfg(all(test, not(feature = "sandbox")))]
+    mod tests {
+        use super::split_conversion_clause;
+
+        #[test]
+        fn leaves_rinks_native_arrow_conversions_alone() {
+            assert_eq!(split_conversion_clause("1 kWh -> J"), ("1 kWh -> J", None));
+        }
+
+        #[test]
+        fn splits_on_the_named_conversion_clause() {
+            assert_eq!(
+                split_conversion_clause("now - epoch => timestamp_fmt(\"%H:%M:%S\")"),
+                ("now - epoch", Some("timestamp_fmt(\"%H:%M:%S\")")));
+        }
+    }
+}
+
+#[cfg(feature = "ircbot")]
+mod ircbot {
+    use super::frontend::{ChatFrontend, ReplyTarget};
+    use irc::client::prelude::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Flood-protection knobs, read from the `options` map of a
+    // server's config file. Any key that's missing falls back to the
+    // previous hardcoded behavior (5 lines, no pacing, no cooldown).
+    struct FloodLimits {
+        max_reply_lines: usize,
+        min_message_interval: Duration,
+        nick_cooldown: Option<Duration>,
+    }
+
+    fn get_option<T: FromStr>(options: Option<&HashMap<String, String>>, key: &str) -> Option<T> {
+        options.and_then(|o| o.get(key)).and_then(|v| v.parse().ok())
+    }
+
+    impl FloodLimits {
+        fn from_options(options: Option<&HashMap<String, String>>) -> FloodLimits {
+            FloodLimits {
+                max_reply_lines: get_option(options, "max_reply_lines").unwrap_or(5),
+                min_message_interval: Duration::from_millis(
+                    get_option(options, "min_message_interval_ms").unwrap_or(0)),
+                nick_cooldown: get_option(options, "nick_cooldown_ms").map(Duration::from_millis),
+            }
+        }
+    }
+
+    pub struct IrcFrontend {
+        server: IrcServer,
+        nick: String,
+        limits: FloodLimits,
+        /// Last time each *asking nick* (not reply target) got a query
+        /// answered, for `nick_cooldown`.
+        last_sent: RefCell<HashMap<String, Instant>>,
+        /// Last time any reply line was sent on this connection, for
+        /// `min_message_interval`.
+        last_send: RefCell<Option<Instant>>,
+    }
+
+    impl IrcFrontend {
+        pub fn connect(config: &str) -> IrcFrontend {
+            let server = IrcServer::new(config).unwrap();
+            server.identify().unwrap();
+            let nick = server.config().nickname.clone().unwrap();
+            let limits = FloodLimits::from_options(server.config().options.as_ref());
+            IrcFrontend {
+                server: server,
+                nick: nick,
+                limits: limits,
+                last_sent: RefCell::new(HashMap::new()),
+                last_send: RefCell::new(None),
+            }
+        }
+    }
+
+    impl ChatFrontend for IrcFrontend {
+        fn nick(&self) -> &str {
+            &self.nick
+        }
+
+        fn max_reply_lines(&self) -> usize {
+            self.limits.max_reply_lines
+        }
+
+        fn incoming<'a>(&'a self) -> Box<Iterator<Item = (ReplyTarget, String, String)> + 'a> {
+            let nick = self.nick.clone();
+            Box::new(self.server.iter().filter_map(move |message| {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(e) => {
+                        println!("{}", e);
+                        return None
                     }
+                };
+                match message.command {
+                    Command::PRIVMSG(ref chan, ref text) => {
+                        let sender = match message.source_nickname() {
+                            Some(sender) => sender.to_owned(),
+                            None => return None
+                        };
+                        let reply_to = if *chan == nick { sender.clone() } else { chan.clone() };
+                        Some((ReplyTarget(reply_to), sender, text.clone()))
+                    },
+                    _ => None
+                }
+            }))
+        }
+
+        // `run` only calls this once a message has already passed the
+        // nick-prefix check, so the cooldown is keyed and updated per
+        // actual bot query, not per PRIVMSG the bot merely observes.
+        fn allow_query(&self, sender: &str) -> bool {
+            let cooldown = match self.limits.nick_cooldown {
+                Some(cooldown) => cooldown,
+                None => return true
+            };
+            let mut last_sent = self.last_sent.borrow_mut();
+            if let Some(&last) = last_sent.get(sender) {
+                if Instant::now() - last < cooldown {
+                    return false
                 }
-            } else if let Err(e) = message {
-                println!("{}", e);
             }
+            last_sent.insert(sender.to_owned(), Instant::now());
+            true
+        }
+
+        fn send(&self, target: &ReplyTarget, line: &str) {
+            let mut last_send = self.last_send.borrow_mut();
+            if let Some(last) = *last_send {
+                let elapsed = Instant::now() - last;
+                if elapsed < self.limits.min_message_interval {
+                    thread::sleep(self.limits.min_message_interval - elapsed);
+                }
+            }
+            self.server.send(Command::NOTICE(target.0.clone(), line.to_owned())).unwrap();
+            *last_send = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(feature = "stdio")]
+mod stdio {
+    use super::frontend::{ChatFrontend, ReplyTarget};
+    use std::io::{self, BufRead};
+
+    /// A line-based stdin/stdout frontend, mainly useful for
+    /// exercising the query-and-reply dispatch without standing up an
+    /// IRC server.
+    pub struct StdioFrontend {
+        stdin: io::Stdin,
+    }
+
+    impl StdioFrontend {
+        pub fn new() -> StdioFrontend {
+            StdioFrontend { stdin: io::stdin() }
         }
     }
 
+    impl ChatFrontend for StdioFrontend {
+        fn nick(&self) -> &str {
+            "rink"
+        }
+
+        fn incoming<'a>(&'a self) -> Box<Iterator<Item = (ReplyTarget, String, String)> + 'a> {
+            Box::new(self.stdin.lock().lines().filter_map(|line| {
+                line.ok().map(|line| (ReplyTarget("stdout".to_owned()), "stdin".to_owned(), line))
+            }))
+        }
+
+        fn send(&self, _target: &ReplyTarget, line: &str) {
+            println!("{}", line);
+        }
+    }
+}
+
+#[cfg(feature = "ircbot")]
+fn main() {
+    use frontend::ChatFrontend;
+    use glob::glob;
+    use std::thread;
+
     let mut threads = vec![];
     for config in glob("servers/*.json").expect("Glob failed") {
         match config {
-            Ok(config) => threads.push(thread::spawn(move || run(config.to_str().unwrap()))),
+            Ok(config) => threads.push(thread::spawn(move || {
+                ircbot::IrcFrontend::connect(config.to_str().unwrap()).run()
+            })),
             Err(e) => println!("{:?}", e)
         }
     }
@@ -73,7 +328,14 @@ fn main() {
     }
 }
 
-#[cfg(not(feature = "ircbot"))]
+#[cfg(all(feature = "stdio", not(feature = "ircbot")))]
+fn main() {
+    use frontend::ChatFrontend;
+
+    stdio::StdioFrontend::new().run();
+}
+
+#[cfg(not(any(feature = "ircbot", feature = "stdio")))]
 fn main() {
-    println!("Rink was not compiled with IRC support.");
+    println!("Rink was not compiled with a chat frontend (enable `ircbot` or `stdio`).");
 }
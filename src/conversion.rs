@@ -0,0 +1,201 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::str::FromStr;
+use std::fmt;
+use chrono::{LocalResult, TimeZone, Utc};
+use context::Context;
+use number::Number;
+use value::Value;
+
+/// A named input/output conversion, independent of rink's usual
+/// unit-driven parsing: a way to read a bare string into a `Value` and
+/// render a `Value` back out as text, e.g. for timestamps or plain
+/// integers that a frontend wants to hand back typed or formatted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conversion {
+    Bytes,
+    String,
+    Int,
+    Float,
+    Bool,
+    Timestamp,
+    TimestampFmt(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownConversion(pub String);
+
+impl fmt::Display for UnknownConversion {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Unknown conversion `{}`", self.0)
+    }
+}
+
+impl FromStr for Conversion {
+    type Err = UnknownConversion;
+
+    fn from_str(s: &str) -> Result<Conversion, UnknownConversion> {
+        if s == "bytes" {
+            Ok(Conversion::Bytes)
+        } else if s == "string" {
+            Ok(Conversion::String)
+        } else if s == "int" {
+            Ok(Conversion::Int)
+        } else if s == "float" {
+            Ok(Conversion::Float)
+        } else if s == "bool" {
+            Ok(Conversion::Bool)
+        } else if s == "timestamp" {
+            Ok(Conversion::Timestamp)
+        } else if s.starts_with("timestamp_fmt(") && s.ends_with(')') {
+            let arg = s["timestamp_fmt(".len()..s.len() - 1].trim().trim_matches('"');
+            Ok(Conversion::TimestampFmt(arg.to_owned()))
+        } else {
+            Err(UnknownConversion(s.to_owned()))
+        }
+    }
+}
+
+impl Conversion {
+    /// Parses a frontend-supplied string into a `Value` according to
+    /// this conversion's rules.
+    pub fn parse(&self, input: &str) -> Result<Value, String> {
+        match *self {
+            Conversion::Bytes | Conversion::String =>
+                Ok(Value::String(input.to_owned())),
+            Conversion::Int => input.parse::<i64>()
+                .map(|v| Value::Number(Number::from(v)))
+                .map_err(|e| format!("Invalid int `{}`: {}", input, e)),
+            Conversion::Float => input.parse::<f64>()
+                .map(|v| Value::Number(Number::from(v)))
+                .map_err(|e| format!("Invalid float `{}`: {}", input, e)),
+            Conversion::Bool => match input {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => Err(format!("Invalid bool `{}`", input)),
+            },
+            Conversion::Timestamp => input.parse::<i64>()
+                .map(|v| Value::Number(Number::from(v)))
+                .map_err(|e| format!("Invalid timestamp `{}`: {}", input, e)),
+            Conversion::TimestampFmt(ref pattern) =>
+                Utc.datetime_from_str(input, pattern)
+                    .map(|dt| Value::Number(Number::from(dt.timestamp())))
+                    .map_err(|e| format!(
+                        "Input `{}` does not match format `{}`: {}",
+                        input, pattern, e)),
+        }
+    }
+
+    /// Formats a `Value` back out to text according to this
+    /// conversion's rules.
+    pub fn format(&self, value: &Value, context: &Context) -> Result<String, String> {
+        match *self {
+            Conversion::Bytes | Conversion::String => match *value {
+                Value::String(ref s) => Ok(s.clone()),
+                ref v => Ok(v.show(context)),
+            },
+            Conversion::Int => {
+                let n = try!(as_epoch_or_scalar(value, context));
+                Ok(format!("{}", n))
+            },
+            Conversion::Float => {
+                let n = try!(as_scalar_f64(value, context));
+                Ok(format!("{}", n))
+            },
+            Conversion::Bool => match *value {
+                Value::Bool(b) => Ok(b.to_string()),
+                ref v => Err(format!("Not a bool: {}", v.show(context))),
+            },
+            Conversion::Timestamp => {
+                let n = try!(as_epoch_or_scalar(value, context));
+                Ok(format!("{}", n))
+            },
+            Conversion::TimestampFmt(ref pattern) => {
+                let n = try!(as_epoch_or_scalar(value, context));
+                match Utc.timestamp_opt(n, 0) {
+                    LocalResult::Single(dt) => Ok(dt.format(pattern).to_string()),
+                    _ => Err(format!("Timestamp out of range: {}", n)),
+                }
+            },
+        }
+    }
+}
+
+/// Coerces a dimensionless `Value::Number` to an integer, for
+/// conversions that treat their input as a count or a Unix timestamp.
+fn as_epoch_or_scalar(value: &Value, context: &Context) -> Result<i64, String> {
+    match *value {
+        Value::Number(ref n) => n.to_int()
+            .ok_or_else(|| format!("Not a dimensionless number: {}", n.show(context))),
+        ref v => Err(format!("Not a number: {}", v.show(context))),
+    }
+}
+
+/// Coerces a dimensionless `Value::Number` to a float, for
+/// `Conversion::Float` so it doesn't truncate like the integer-valued
+/// conversions above.
+fn as_scalar_f64(value: &Value, context: &Context) -> Result<f64, String> {
+    match *value {
+        Value::Number(ref n) => n.to_f64()
+            .ok_or_else(|| format!("Not a dimensionless number: {}", n.show(context))),
+        ref v => Err(format!("Not a number: {}", v.show(context))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_recognizes_every_builtin() {
+        assert_eq!("bytes".parse(), Ok(Conversion::Bytes));
+        assert_eq!("string".parse(), Ok(Conversion::String));
+        assert_eq!("int".parse(), Ok(Conversion::Int));
+        assert_eq!("float".parse(), Ok(Conversion::Float));
+        assert_eq!("bool".parse(), Ok(Conversion::Bool));
+        assert_eq!("timestamp".parse(), Ok(Conversion::Timestamp));
+        assert_eq!(
+            "timestamp_fmt(\"%Y-%m-%d\")".parse(),
+            Ok(Conversion::TimestampFmt("%Y-%m-%d".to_owned())));
+    }
+
+    #[test]
+    fn from_str_rejects_unknown_names() {
+        let err = "nonsense".parse::<Conversion>().unwrap_err();
+        assert_eq!(err, UnknownConversion("nonsense".to_owned()));
+        assert_eq!(err.to_string(), "Unknown conversion `nonsense`");
+    }
+
+    #[test]
+    fn parse_int_happy_and_error_paths() {
+        match Conversion::Int.parse("42") {
+            Ok(Value::Number(_)) => {},
+            other => panic!("expected a dimensionless number, got {:?}", other),
+        }
+        assert!(Conversion::Int.parse("not a number").is_err());
+    }
+
+    #[test]
+    fn parse_bool_happy_and_error_paths() {
+        assert_eq!(Conversion::Bool.parse("true"), Ok(Value::Bool(true)));
+        assert_eq!(Conversion::Bool.parse("false"), Ok(Value::Bool(false)));
+        assert!(Conversion::Bool.parse("maybe").is_err());
+    }
+
+    #[test]
+    fn format_timestamp_fmt_rejects_out_of_range_instead_of_panicking() {
+        let context = Context::new();
+        let huge = Value::Number(Number::from(i64::max_value()));
+        let conversion = Conversion::TimestampFmt("%Y-%m-%d".to_owned());
+        assert!(conversion.format(&huge, &context).is_err());
+    }
+
+    #[test]
+    fn float_round_trips_without_truncating() {
+        let context = Context::new();
+        let value = Conversion::Float.parse("3.14").unwrap();
+        assert_eq!(Conversion::Float.format(&value, &context), Ok("3.14".to_owned()));
+    }
+}
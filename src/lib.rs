@@ -0,0 +1,12 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// NOTE: this file declares only the `substance` and `conversion`
+// modules; the modules they import (`context`, `number`, `value`,
+// `reply`, ...) are not declared here.
+pub mod substance;
+pub mod conversion;
+
+pub use substance::Substance;
+pub use conversion::{Conversion, UnknownConversion};
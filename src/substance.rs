@@ -6,8 +6,9 @@ use context::Context;
 use number::Number;
 use value::Show;
 use std::collections::BTreeMap;
+use std::sync::Arc;
 use reply::{PropertyReply, SubstanceReply};
-use std::ops::{Mul, Div};
+use std::ops::{Add, Sub, Mul, Div};
 use std::iter::once;
 
 #[derive(Debug, Clone)]
@@ -19,10 +20,21 @@ pub struct Property {
     pub doc: Option<String>,
 }
 
+/// The shared, immutable property table for a substance. Cloning a
+/// `Substance` only bumps the `Arc` refcount instead of deep-cloning
+/// every property, since the table never changes once built. The map
+/// itself is behind its own `Arc` so that `rename` can swap in a new
+/// display name without deep-cloning every `Property` either.
+#[derive(Debug, Clone)]
+pub struct Properties {
+    pub name: String,
+    pub properties: Arc<BTreeMap<String, Property>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Substance {
     pub amount: Number,
-    pub properties: BTreeMap<String, Property>,
+    pub properties: Arc<Properties>,
 }
 
 pub enum SubstanceGetError {
@@ -33,7 +45,7 @@ pub enum SubstanceGetError {
 impl Substance {
     pub fn get(&self, name: &str) -> Result<Number, SubstanceGetError> {
         if self.amount.1.len() == 0 {
-            self.properties.get(name)
+            self.properties.properties.get(name)
                 .ok_or_else(|| SubstanceGetError::Generic(format!(
                     "No such property {}", name)))
                 .map(|prop| {
@@ -41,7 +53,7 @@ impl Substance {
                         .expect("Non-zero property")
                 })
         } else {
-            for (_name, prop) in &self.properties {
+            for (_name, prop) in &self.properties.properties {
                 if name == prop.output_name {
                     let input = try!(
                         (&prop.input / &self.amount).ok_or_else(
@@ -77,10 +89,26 @@ impl Substance {
         }
     }
 
+    /// Returns a copy of this substance carrying a new display name,
+    /// e.g. to label a scaled quantity like `1 kg water` distinctly
+    /// from the `water` it was derived from. The property map is
+    /// shared via its own `Arc`, so this only allocates a new
+    /// `Properties`/`Arc` pair, not the map or any `Property` in it.
+    pub fn rename(self, name: String) -> Substance {
+        let properties = Properties {
+            name: name,
+            properties: self.properties.properties.clone(),
+        };
+        Substance {
+            amount: self.amount,
+            properties: Arc::new(properties),
+        }
+    }
+
     pub fn to_reply(&self, context: &Context) -> Result<SubstanceReply, String> {
         if self.amount.1.len() == 0 {
             Ok(SubstanceReply {
-                properties: try!(self.properties.iter().map(|(k, v)| {
+                properties: try!(self.properties.properties.iter().map(|(k, v)| {
                     let (input, output) = if v.input.1.len() == 0 {
                         let res = (&v.output * &self.amount).unwrap();
                         (None, try!((&res / &v.input)
@@ -150,7 +178,7 @@ impl Substance {
             Ok(SubstanceReply {
                 properties: try!(
                     once(Ok(Some(amount)))
-                        .chain(self.properties.iter().map(func))
+                        .chain(self.properties.properties.iter().map(func))
                         .collect::<Result<Vec<Option<PropertyReply>>, String>>())
                     .into_iter()
                     .filter_map(|x| x)
@@ -172,6 +200,8 @@ impl Show for Substance {
 impl<'a, 'b> Mul<&'b Number> for &'a Substance {
     type Output = Result<Substance, String>;
 
+    // Only the `Arc` handle is cloned here; the property table itself
+    // is shared with `self` and never duplicated.
     fn mul(self, other: &'b Number) -> Self::Output {
         Ok(Substance {
             amount: try!((&self.amount * other).ok_or_else(
@@ -184,6 +214,8 @@ impl<'a, 'b> Mul<&'b Number> for &'a Substance {
 impl<'a, 'b> Div<&'b Number> for &'a Substance {
     type Output = Result<Substance, String>;
 
+    // Only the `Arc` handle is cloned here; the property table itself
+    // is shared with `self` and never duplicated.
     fn div(self, other: &'b Number) -> Self::Output {
         Ok(Substance {
             amount: try!((&self.amount / other).ok_or_else(
@@ -192,3 +224,120 @@ impl<'a, 'b> Div<&'b Number> for &'a Substance {
         })
     }
 }
+
+/// Checks that two property tables describe the same substance: the
+/// same name and the same set of property names. The `Add`/`Sub`
+/// impls try the cheaper `Arc::ptr_eq` check first (the common case,
+/// since properties are loaded once and shared via `Arc`).
+fn same_substance(a: &Properties, b: &Properties) -> bool {
+    a.name == b.name && a.properties.len() == b.properties.len() &&
+        a.properties.keys().eq(b.properties.keys())
+}
+
+impl<'a, 'b> Add<&'b Substance> for &'a Substance {
+    // Reuses `SubstanceGetError` (rather than a pre-rendered `String`)
+    // so that, like `get`'s own conformance errors, the mismatched
+    // amounts can be rendered with `Number::show(context)` by whatever
+    // code surfaces the error, instead of a raw `Debug` dump.
+    type Output = Result<Substance, SubstanceGetError>;
+
+    fn add(self, other: &'b Substance) -> Self::Output {
+        if !Arc::ptr_eq(&self.properties, &other.properties) &&
+            !same_substance(&self.properties, &other.properties) {
+            return Err(SubstanceGetError::Generic(format!(
+                "Cannot add substances of different types: {} and {}",
+                self.properties.name, other.properties.name)))
+        }
+        if self.amount.1 != other.amount.1 {
+            return Err(SubstanceGetError::Conformance(
+                self.amount.clone(), other.amount.clone()))
+        }
+        Ok(Substance {
+            amount: try!((&self.amount + &other.amount).ok_or_else(
+                || SubstanceGetError::Generic(
+                    "Addition of numbers should not fail".to_owned()))),
+            properties: self.properties.clone(),
+        })
+    }
+}
+
+impl<'a, 'b> Sub<&'b Substance> for &'a Substance {
+    type Output = Result<Substance, SubstanceGetError>;
+
+    fn sub(self, other: &'b Substance) -> Self::Output {
+        if !Arc::ptr_eq(&self.properties, &other.properties) &&
+            !same_substance(&self.properties, &other.properties) {
+            return Err(SubstanceGetError::Generic(format!(
+                "Cannot subtract substances of different types: {} and {}",
+                self.properties.name, other.properties.name)))
+        }
+        if self.amount.1 != other.amount.1 {
+            return Err(SubstanceGetError::Conformance(
+                self.amount.clone(), other.amount.clone()))
+        }
+        Ok(Substance {
+            amount: try!((&self.amount - &other.amount).ok_or_else(
+                || SubstanceGetError::Generic(
+                    "Subtraction of numbers should not fail".to_owned()))),
+            properties: self.properties.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::collections::BTreeMap;
+
+    fn substance(name: &str) -> Substance {
+        let properties = Properties {
+            name: name.to_owned(),
+            properties: Arc::new(BTreeMap::new()),
+        };
+        Substance {
+            amount: Number::one(),
+            properties: Arc::new(properties),
+        }
+    }
+
+    #[test]
+    fn add_mixes_shared_substance() {
+        let a = substance("water");
+        let b = Substance {
+            amount: Number::one(),
+            properties: a.properties.clone(),
+        };
+        assert!((&a + &b).is_ok());
+    }
+
+    #[test]
+    fn sub_mixes_shared_substance() {
+        let a = substance("water");
+        let b = Substance {
+            amount: Number::one(),
+            properties: a.properties.clone(),
+        };
+        assert!((&a - &b).is_ok());
+    }
+
+    #[test]
+    fn add_rejects_different_substances() {
+        let water = substance("water");
+        let ethanol = substance("ethanol");
+        match &water + &ethanol {
+            Err(SubstanceGetError::Generic(_)) => {},
+            _ => panic!("expected a Generic error for mismatched substances"),
+        }
+    }
+
+    #[test]
+    fn sub_rejects_different_substances() {
+        let water = substance("water");
+        let ethanol = substance("ethanol");
+        match &water - &ethanol {
+            Err(SubstanceGetError::Generic(_)) => {},
+            _ => panic!("expected a Generic error for mismatched substances"),
+        }
+    }
+}